@@ -0,0 +1,131 @@
+use matrix_sdk_crypto::olm::InboundGroupSession;
+
+use crate::{
+    error::{FailedSession, KeyBackupError, RoomKeyRestoreResult},
+    machine::OlmMachine,
+};
+
+#[uniffi::export]
+impl OlmMachine {
+    /// Start backing up room keys to `backup_version`, trusting
+    /// `backup_public_key` to encrypt sessions towards from now on.
+    pub fn enable_backup(
+        &self,
+        backup_version: &str,
+        backup_public_key: &[u8],
+    ) -> Result<(), KeyBackupError> {
+        self.inner
+            .store()
+            .save_backup_info(backup_version, backup_public_key)?;
+
+        Ok(())
+    }
+
+    /// Encrypt a single megolm session towards `backup_public_key`, ready to
+    /// be uploaded to the homeserver. The key recorded by
+    /// [`OlmMachine::enable_backup`] is not consulted.
+    pub fn backup_room_key(
+        &self,
+        session: &InboundGroupSession,
+        backup_public_key: &[u8],
+    ) -> Result<Vec<u8>, KeyBackupError> {
+        let session_data = serde_json::to_vec(&session.export())?;
+
+        matrix_sdk_crypto::backups::encrypt_for_backup(&session_data, backup_public_key)
+            .map_err(|_| KeyBackupError::Encryption)
+    }
+
+    /// Restore sessions fetched from the server-side backup, rejecting the
+    /// whole restore if the backup has since been rotated or rolled back,
+    /// but collecting any per-session decrypt/import failures into the
+    /// returned [`RoomKeyRestoreResult`] instead of abandoning the rest of
+    /// the batch.
+    pub fn restore_backup(
+        &self,
+        backup_version: &str,
+        recovery_key: &str,
+        encrypted_sessions: Vec<Vec<u8>>,
+    ) -> Result<RoomKeyRestoreResult, KeyBackupError> {
+        let stored_version = self.inner.store().backup_version()?;
+        check_backup_version(backup_version, stored_version.as_deref())?;
+
+        let mut imported_count = 0;
+        let mut failed_sessions = Vec::new();
+
+        for (index, encrypted) in encrypted_sessions.into_iter().enumerate() {
+            match decrypt_and_import_session(&self.inner, &encrypted, recovery_key) {
+                Ok(()) => imported_count += 1,
+                Err(error) => failed_sessions.push(FailedSession {
+                    session_id: index.to_string(),
+                    error: error.to_string(),
+                }),
+            }
+        }
+
+        Ok(RoomKeyRestoreResult {
+            imported_count,
+            failed_sessions,
+        })
+    }
+}
+
+fn decrypt_and_import_session(
+    machine: &matrix_sdk_crypto::OlmMachine,
+    encrypted: &[u8],
+    recovery_key: &str,
+) -> Result<(), KeyBackupError> {
+    let decrypted = matrix_sdk_crypto::backups::decrypt_from_backup(encrypted, recovery_key)
+        .map_err(|_| KeyBackupError::InvalidRecoveryKey)?;
+    let session_data = serde_json::from_slice(&decrypted)?;
+
+    machine.store().import_room_keys(vec![session_data])?;
+
+    Ok(())
+}
+
+/// Check `backup_version` against the store's currently recorded version,
+/// kept separate from I/O so the mismatch case can be exercised without a
+/// real store.
+fn check_backup_version(
+    backup_version: &str,
+    stored_version: Option<&str>,
+) -> Result<(), KeyBackupError> {
+    if stored_version == Some(backup_version) {
+        Ok(())
+    } else {
+        Err(KeyBackupError::BackupVersionMismatch {
+            backup_version: backup_version.to_owned(),
+            expected_version: stored_version.unwrap_or_default().to_owned(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matching_backup_version_passes() {
+        assert!(check_backup_version("v2", Some("v2")).is_ok());
+    }
+
+    #[test]
+    fn mismatched_backup_version_is_rejected() {
+        let error = check_backup_version("v2", Some("v1")).unwrap_err();
+        assert!(matches!(
+            error,
+            KeyBackupError::BackupVersionMismatch { backup_version, expected_version }
+                if backup_version == "v2" && expected_version == "v1"
+        ));
+    }
+
+    #[test]
+    fn missing_backup_version_is_rejected_with_an_empty_expected_version() {
+        let error = check_backup_version("v2", None).unwrap_err();
+        assert!(matches!(
+            error,
+            KeyBackupError::BackupVersionMismatch { backup_version, expected_version }
+                if backup_version == "v2" && expected_version.is_empty()
+        ));
+    }
+}
@@ -1,4 +1,4 @@
-use matrix_sdk_common::identifiers::Error as RumaIdentifierError;
+use matrix_sdk_common::identifiers::{Error as RumaIdentifierError, UserId};
 use matrix_sdk_crypto::{
     store::CryptoStoreError as InnerStoreError, KeyExportError, MegolmError, OlmError,
 };
@@ -19,6 +19,61 @@ pub enum KeyImportError {
     CryptoStore(#[from] InnerStoreError),
 }
 
+#[derive(Debug, thiserror::Error, uniffi::Error)]
+#[uniffi(flat_error)]
+pub enum RoomKeyExportError {
+    #[error(transparent)]
+    Export(#[from] KeyExportError),
+    #[error(transparent)]
+    CryptoStore(#[from] InnerStoreError),
+}
+
+/// A session that couldn't be carried through an export or restore, along
+/// with why, so the operation can report it instead of failing outright.
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct FailedSession {
+    /// The session's id for an export; for a backup restore, the position
+    /// of the encrypted payload in the restored batch.
+    pub session_id: String,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct RoomKeyExportResult {
+    pub exported_count: u64,
+    pub failed_sessions: Vec<FailedSession>,
+}
+
+#[derive(Debug, thiserror::Error, uniffi::Error)]
+#[uniffi(flat_error)]
+pub enum KeyBackupError {
+    #[error(transparent)]
+    CryptoStore(#[from] InnerStoreError),
+    #[error("the supplied recovery key is invalid for this backup")]
+    InvalidRecoveryKey,
+    #[error("the backup version {backup_version} does not match the expected version {expected_version}")]
+    BackupVersionMismatch {
+        backup_version: String,
+        expected_version: String,
+    },
+    #[error("the backup auth data signature could not be verified")]
+    InvalidSignature,
+    #[error("the session could not be encrypted towards the backup's public key")]
+    Encryption,
+    #[error(transparent)]
+    Serialization(#[from] serde_json::Error),
+}
+
+/// The outcome of restoring a batch of sessions fetched from a server-side
+/// backup: how many made it in, and which didn't, mirroring
+/// [`RoomKeyExportResult`] so a bad session never discards the rest of the
+/// restore.
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct RoomKeyRestoreResult {
+    pub imported_count: u64,
+    pub failed_sessions: Vec<FailedSession>,
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum CryptoStoreError {
     #[error(transparent)]
@@ -31,7 +86,8 @@ pub enum CryptoStoreError {
     Identifier(#[from] RumaIdentifierError),
 }
 
-#[derive(Debug, thiserror::Error)]
+#[derive(Debug, thiserror::Error, uniffi::Error)]
+#[uniffi(flat_error)]
 pub enum DecryptionError {
     #[error(transparent)]
     Serialization(#[from] serde_json::Error),
@@ -39,4 +95,149 @@ pub enum DecryptionError {
     Identifier(#[from] RumaIdentifierError),
     #[error(transparent)]
     Megolm(#[from] MegolmError),
+    #[error("the sender's identity does not meet the required trust level: {code:?}")]
+    VerificationViolation { code: VerificationViolationCode },
+    #[error("the sender withheld the room key: {code:?} ({reason})")]
+    Withheld {
+        code: WithheldCode,
+        reason: String,
+        sender: UserId,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, uniffi::Enum)]
+pub enum WithheldCode {
+    Blacklisted,
+    Unverified,
+    Unauthorised,
+    Unavailable,
+    NoOlm,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, uniffi::Enum)]
+pub enum TrustRequirement {
+    Untrusted,
+    CrossSignedOrLegacy,
+    CrossSigned,
+}
+
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct DecryptionSettings {
+    pub sender_device_trust_requirement: TrustRequirement,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, uniffi::Enum)]
+pub enum VerificationViolationCode {
+    ChangedIdentity,
+    NotCrossSigned,
+}
+
+/// A machine-readable classification of why an event failed to decrypt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, uniffi::Enum)]
+pub enum DecryptionFailureCode {
+    UnknownMegolmSession,
+    UnableToDecrypt,
+    MalformedEncryptedEvent,
+    MismatchedSender,
+}
+
+impl From<&MegolmError> for DecryptionFailureCode {
+    fn from(error: &MegolmError) -> Self {
+        match error {
+            MegolmError::MissingRoomKey => Self::UnknownMegolmSession,
+            MegolmError::Decryption(_) => Self::UnableToDecrypt,
+            MegolmError::EventError(_) | MegolmError::JsonError(_) => Self::MalformedEncryptedEvent,
+            MegolmError::MismatchedIdentityKeys { .. } => Self::MismatchedSender,
+            _ => Self::UnableToDecrypt,
+        }
+    }
+}
+
+impl DecryptionError {
+    /// Classify this error into a [`DecryptionFailureCode`] that the
+    /// application can use to pick an appropriate decryption shield,
+    /// instead of pattern-matching on the opaque inner error.
+    pub fn failure_code(&self) -> Option<DecryptionFailureCode> {
+        match self {
+            DecryptionError::Megolm(e) => Some(DecryptionFailureCode::from(e)),
+            DecryptionError::Serialization(_)
+            | DecryptionError::Identifier(_)
+            | DecryptionError::VerificationViolation { .. }
+            | DecryptionError::Withheld { .. } => None,
+        }
+    }
+
+    /// The [`VerificationViolationCode`] carried by this error, if it's a
+    /// [`DecryptionError::VerificationViolation`].
+    pub fn violation_code(&self) -> Option<VerificationViolationCode> {
+        match self {
+            DecryptionError::VerificationViolation { code } => Some(*code),
+            _ => None,
+        }
+    }
+
+    /// The [`WithheldCode`] carried by this error, if it's a
+    /// [`DecryptionError::Withheld`].
+    pub fn withheld_code(&self) -> Option<WithheldCode> {
+        match self {
+            DecryptionError::Withheld { code, .. } => Some(*code),
+            _ => None,
+        }
+    }
+}
+
+/// FFI-facing accessor for [`DecryptionError::failure_code`], since plain
+/// methods on an error type aren't reachable from the bindings on their own.
+#[uniffi::export]
+pub fn decryption_failure_code(error: &DecryptionError) -> Option<DecryptionFailureCode> {
+    error.failure_code()
+}
+
+/// FFI-facing accessor for [`DecryptionError::violation_code`].
+#[uniffi::export]
+pub fn decryption_violation_code(error: &DecryptionError) -> Option<VerificationViolationCode> {
+    error.violation_code()
+}
+
+/// FFI-facing accessor for [`DecryptionError::withheld_code`].
+#[uniffi::export]
+pub fn decryption_withheld_code(error: &DecryptionError) -> Option<WithheldCode> {
+    error.withheld_code()
+}
+
+#[cfg(test)]
+mod tests {
+    use matrix_sdk_common::events::EventTypeError;
+
+    use super::*;
+
+    #[test]
+    fn missing_room_key_is_unknown_session() {
+        let code = DecryptionFailureCode::from(&MegolmError::MissingRoomKey);
+        assert_eq!(code, DecryptionFailureCode::UnknownMegolmSession);
+    }
+
+    #[test]
+    fn malformed_json_is_malformed_event() {
+        let inner = serde_json::from_str::<serde_json::Value>("{").unwrap_err();
+        let code = DecryptionFailureCode::from(&MegolmError::JsonError(inner));
+        assert_eq!(code, DecryptionFailureCode::MalformedEncryptedEvent);
+    }
+
+    #[test]
+    fn event_error_is_malformed_event() {
+        let inner = EventTypeError::new("m.room.encrypted");
+        let code = DecryptionFailureCode::from(&MegolmError::EventError(inner));
+        assert_eq!(code, DecryptionFailureCode::MalformedEncryptedEvent);
+    }
+
+    #[test]
+    fn mismatched_identity_keys_is_mismatched_sender() {
+        let error = MegolmError::MismatchedIdentityKeys {
+            expected_curve25519_key: Default::default(),
+            received_curve25519_key: Default::default(),
+        };
+        let code = DecryptionFailureCode::from(&error);
+        assert_eq!(code, DecryptionFailureCode::MismatchedSender);
+    }
 }
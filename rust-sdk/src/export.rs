@@ -0,0 +1,112 @@
+use matrix_sdk_crypto::encrypt_key_export;
+
+use crate::{
+    error::{FailedSession, RoomKeyExportError, RoomKeyExportResult},
+    machine::OlmMachine,
+};
+
+/// Reported once per session as an export streams out, so a UI can show a
+/// progress bar across accounts with tens of thousands of sessions instead
+/// of blocking until the whole export finishes.
+pub trait ExportProgressListener: Send + Sync {
+    fn on_progress(&self, exported: u64, total: u64);
+}
+
+#[uniffi::export]
+impl OlmMachine {
+    /// Export all known room keys into a passphrase-encrypted key export
+    /// file, streaming progress through
+    /// `progress_listener` and collecting any per-session failures into the
+    /// returned [`RoomKeyExportResult`] instead of aborting the whole export.
+    pub fn export_room_keys(
+        &self,
+        passphrase: &str,
+        progress_listener: &dyn ExportProgressListener,
+    ) -> Result<(Vec<u8>, RoomKeyExportResult), RoomKeyExportError> {
+        let sessions = self.inner.store().export_room_keys(|_| true)?;
+
+        let (exportable, failed_sessions) = partition_with_progress(
+            sessions,
+            |session| serde_json::to_value(session).map(|_| ()),
+            |session| session.session_id().to_owned(),
+            |exported, total| progress_listener.on_progress(exported, total),
+        );
+
+        let encrypted = encrypt_key_export(&exportable, passphrase, 500_000)?;
+
+        Ok((
+            encrypted,
+            RoomKeyExportResult {
+                exported_count: exportable.len() as u64,
+                failed_sessions,
+            },
+        ))
+    }
+}
+
+/// Try `op` on each item, keeping the item on success and recording a
+/// [`FailedSession`] on failure, reporting `(done, total)` progress after
+/// every item either way so a single bad item never aborts the rest.
+fn partition_with_progress<T, E: std::fmt::Display>(
+    items: Vec<T>,
+    op: impl Fn(&T) -> Result<(), E>,
+    label: impl Fn(&T) -> String,
+    mut on_progress: impl FnMut(u64, u64),
+) -> (Vec<T>, Vec<FailedSession>) {
+    let total = items.len() as u64;
+    let mut succeeded = Vec::with_capacity(items.len());
+    let mut failed = Vec::new();
+
+    for (index, item) in items.into_iter().enumerate() {
+        match op(&item) {
+            Ok(()) => succeeded.push(item),
+            Err(error) => failed.push(FailedSession {
+                session_id: label(&item),
+                error: error.to_string(),
+            }),
+        }
+
+        on_progress(index as u64 + 1, total);
+    }
+
+    (succeeded, failed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn successes_and_failures_are_partitioned() {
+        let (succeeded, failed) = partition_with_progress(
+            vec![1, 2, 3, 4],
+            |n| if n % 2 == 0 { Ok(()) } else { Err("odd") },
+            |n| n.to_string(),
+            |_, _| {},
+        );
+
+        assert_eq!(succeeded, vec![2, 4]);
+        assert_eq!(
+            failed
+                .iter()
+                .map(|f| f.session_id.as_str())
+                .collect::<Vec<_>>(),
+            vec!["1", "3"]
+        );
+        assert!(failed.iter().all(|f| f.error == "odd"));
+    }
+
+    #[test]
+    fn progress_is_reported_once_per_item_regardless_of_outcome() {
+        let mut progress = Vec::new();
+
+        partition_with_progress(
+            vec![1, 2, 3],
+            |n| if *n == 2 { Err("boom") } else { Ok(()) },
+            |n| n.to_string(),
+            |done, total| progress.push((done, total)),
+        );
+
+        assert_eq!(progress, vec![(1, 3), (2, 3), (3, 3)]);
+    }
+}
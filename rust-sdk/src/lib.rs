@@ -0,0 +1,10 @@
+uniffi::setup_scaffolding!();
+
+mod backup;
+mod error;
+mod export;
+mod machine;
+
+pub use error::*;
+pub use export::ExportProgressListener;
+pub use machine::OlmMachine;
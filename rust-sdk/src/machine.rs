@@ -0,0 +1,237 @@
+use matrix_sdk_common::identifiers::RoomId;
+use matrix_sdk_crypto::{MegolmError, OlmMachine as InnerMachine};
+
+use crate::error::{
+    DecryptionError, DecryptionSettings, TrustRequirement, VerificationViolationCode, WithheldCode,
+};
+
+/// Thin wrapper around the crypto-sdk's `OlmMachine`, exposed to the
+/// Kotlin/Swift bindings.
+#[derive(uniffi::Object)]
+pub struct OlmMachine {
+    pub(crate) inner: InnerMachine,
+}
+
+#[uniffi::export]
+impl OlmMachine {
+    /// Decrypt a room event, honouring `settings.sender_device_trust_requirement`
+    /// instead of unconditionally trusting whatever sender the ciphertext
+    /// claims, and reporting a deliberately withheld session as
+    /// [`DecryptionError::Withheld`] rather than a generic missing-session
+    /// error.
+    pub fn decrypt_room_event(
+        &self,
+        event: &str,
+        room_id: &RoomId,
+        settings: &DecryptionSettings,
+    ) -> Result<String, DecryptionError> {
+        let decrypted = match self.inner.decrypt_room_event(event, room_id) {
+            Ok(decrypted) => decrypted,
+            Err(MegolmError::MissingRoomKey) => {
+                return Err(self.missing_session_error(room_id, event))
+            }
+            Err(error) => return Err(error.into()),
+        };
+
+        let trust = SenderTrustState::from_decrypted_event(&decrypted);
+
+        if let Some(code) = verification_violation(&trust, settings.sender_device_trust_requirement)
+        {
+            return Err(DecryptionError::VerificationViolation { code });
+        }
+
+        Ok(decrypted.event.json().to_string())
+    }
+}
+
+impl OlmMachine {
+    /// Consult the stored `m.room_key.withheld` events for `(room_id,
+    /// session_id)` and translate a match into a typed
+    /// [`DecryptionError::Withheld`], falling back to the generic megolm
+    /// error when the sender never told us why (or the event's own
+    /// `session_id` can't be parsed out, which also means we have no idea
+    /// what a caller-supplied session id would even be checked against).
+    fn missing_session_error(&self, room_id: &RoomId, event: &str) -> DecryptionError {
+        let Ok(session_id) = megolm_session_id(event) else {
+            return DecryptionError::Megolm(MegolmError::MissingRoomKey);
+        };
+
+        match self.inner.store().get_withheld_info(room_id, &session_id) {
+            Some(withheld) => DecryptionError::Withheld {
+                code: withheld_code_from_str(withheld.code.as_str()),
+                reason: withheld.reason.unwrap_or_default(),
+                sender: withheld.sender,
+            },
+            None => DecryptionError::Megolm(MegolmError::MissingRoomKey),
+        }
+    }
+}
+
+/// Pull the megolm `session_id` out of an `m.room.encrypted` event's own
+/// content, rather than trusting a caller-supplied value that could belong
+/// to a different event.
+fn megolm_session_id(event: &str) -> Result<String, serde_json::Error> {
+    #[derive(serde::Deserialize)]
+    struct MegolmContent {
+        session_id: String,
+    }
+
+    #[derive(serde::Deserialize)]
+    struct EncryptedEvent {
+        content: MegolmContent,
+    }
+
+    let event: EncryptedEvent = serde_json::from_str(event)?;
+    Ok(event.content.session_id)
+}
+
+/// The parts of a decrypted event's sender trust state that
+/// `verification_violation` needs to make its decision, extracted out of the
+/// crypto-sdk's decrypted-event type so the decision logic can be unit
+/// tested without constructing one.
+struct SenderTrustState {
+    identity_changed_after_verification: bool,
+    trusted_or_legacy: bool,
+    cross_signed: bool,
+}
+
+impl SenderTrustState {
+    fn from_decrypted_event(
+        decrypted: &matrix_sdk_crypto::types::events::DecryptedRoomEvent,
+    ) -> Self {
+        Self {
+            identity_changed_after_verification: decrypted
+                .sender_identity_changed_after_verification(),
+            trusted_or_legacy: decrypted.sender_trusted_or_legacy(),
+            cross_signed: decrypted.sender_cross_signed(),
+        }
+    }
+}
+
+fn verification_violation(
+    trust: &SenderTrustState,
+    requirement: TrustRequirement,
+) -> Option<VerificationViolationCode> {
+    if trust.identity_changed_after_verification {
+        return Some(VerificationViolationCode::ChangedIdentity);
+    }
+
+    match requirement {
+        TrustRequirement::Untrusted => None,
+        TrustRequirement::CrossSignedOrLegacy if trust.trusted_or_legacy => None,
+        TrustRequirement::CrossSigned if trust.cross_signed => None,
+        TrustRequirement::CrossSignedOrLegacy | TrustRequirement::CrossSigned => {
+            Some(VerificationViolationCode::NotCrossSigned)
+        }
+    }
+}
+
+/// Map the wire-format `m.room_key.withheld` code to our typed
+/// [`WithheldCode`], defaulting to [`WithheldCode::Unavailable`] for codes we
+/// don't recognise rather than failing the whole decrypt.
+fn withheld_code_from_str(code: &str) -> WithheldCode {
+    match code {
+        "m.blacklisted" => WithheldCode::Blacklisted,
+        "m.unverified" => WithheldCode::Unverified,
+        "m.unauthorised" => WithheldCode::Unauthorised,
+        "m.unavailable" => WithheldCode::Unavailable,
+        "m.no_olm" => WithheldCode::NoOlm,
+        _ => WithheldCode::Unavailable,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trust(
+        identity_changed_after_verification: bool,
+        trusted_or_legacy: bool,
+        cross_signed: bool,
+    ) -> SenderTrustState {
+        SenderTrustState {
+            identity_changed_after_verification,
+            trusted_or_legacy,
+            cross_signed,
+        }
+    }
+
+    #[test]
+    fn untrusted_requirement_passes_without_an_identity_change() {
+        let code = verification_violation(&trust(false, false, false), TrustRequirement::Untrusted);
+        assert_eq!(code, None);
+    }
+
+    #[test]
+    fn changed_identity_overrides_the_requirement() {
+        let code = verification_violation(&trust(true, true, true), TrustRequirement::Untrusted);
+        assert_eq!(code, Some(VerificationViolationCode::ChangedIdentity));
+    }
+
+    #[test]
+    fn cross_signed_or_legacy_requires_trust() {
+        let violating = verification_violation(
+            &trust(false, false, false),
+            TrustRequirement::CrossSignedOrLegacy,
+        );
+        let satisfied = verification_violation(
+            &trust(false, true, false),
+            TrustRequirement::CrossSignedOrLegacy,
+        );
+
+        assert_eq!(violating, Some(VerificationViolationCode::NotCrossSigned));
+        assert_eq!(satisfied, None);
+    }
+
+    #[test]
+    fn cross_signed_requires_cross_signing_specifically() {
+        let violating =
+            verification_violation(&trust(false, true, false), TrustRequirement::CrossSigned);
+        let satisfied =
+            verification_violation(&trust(false, false, true), TrustRequirement::CrossSigned);
+
+        assert_eq!(violating, Some(VerificationViolationCode::NotCrossSigned));
+        assert_eq!(satisfied, None);
+    }
+
+    #[test]
+    fn recognised_withheld_codes_map_to_their_variant() {
+        assert_eq!(
+            withheld_code_from_str("m.blacklisted"),
+            WithheldCode::Blacklisted
+        );
+        assert_eq!(
+            withheld_code_from_str("m.unverified"),
+            WithheldCode::Unverified
+        );
+        assert_eq!(
+            withheld_code_from_str("m.unauthorised"),
+            WithheldCode::Unauthorised
+        );
+        assert_eq!(
+            withheld_code_from_str("m.unavailable"),
+            WithheldCode::Unavailable
+        );
+        assert_eq!(withheld_code_from_str("m.no_olm"), WithheldCode::NoOlm);
+    }
+
+    #[test]
+    fn unrecognised_withheld_code_falls_back_to_unavailable() {
+        assert_eq!(
+            withheld_code_from_str("m.something_future"),
+            WithheldCode::Unavailable
+        );
+    }
+
+    #[test]
+    fn megolm_session_id_is_read_from_the_event_content() {
+        let event = r#"{"content":{"session_id":"abc123"},"type":"m.room.encrypted"}"#;
+        assert_eq!(megolm_session_id(event).unwrap(), "abc123");
+    }
+
+    #[test]
+    fn megolm_session_id_rejects_events_without_one() {
+        let event = r#"{"content":{},"type":"m.room.encrypted"}"#;
+        assert!(megolm_session_id(event).is_err());
+    }
+}